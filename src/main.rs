@@ -1,13 +1,45 @@
 use bracket_lib::prelude::*;
+use std::collections::VecDeque;
+use std::fs;
 
 // constants to better manage aspects of the game - note constants NEED types
 const SCREEN_WIDTH: i32 = 40;
 const SCREEN_HEIGHT: i32 = 25;
 const FRAME_DURATION: f32 = 75.0;
+// minimum world-space gap between the start of one obstacle and the next
+const SPACING: i32 = 20;
+// where the all-time best score is persisted between runs
+const HIGH_SCORE_FILE: &str = "flappy_score.dat";
 
 // additional constant for the Dragon sprite
 const DRAGON_FRAMES: [u16; 6] = [64, 1, 2, 3, 2, 1];
 
+// starting size of the gap an obstacle leaves for the player to fly through
+const BASE_GAP: i32 = 10;
+// the scroll speed never drops below this, however high the score climbs
+const MIN_FRAME_DURATION: f32 = 35.0;
+
+// works out how hard the game should be for a given score: the gap narrows and the
+// world scrolls faster the further the player gets, keeping both tunable in one place
+fn difficulty(score: i32) -> (i32, f32) {
+    let gap = i32::max(2, BASE_GAP - score / 3); // eases down more gradually than a flat subtraction
+    let frame_duration = f32::max(MIN_FRAME_DURATION, FRAME_DURATION - score as f32 * 2.0);
+    (gap, frame_duration)
+}
+
+// loads the persisted high score, defaulting to 0 if the file is missing or unparseable
+fn load_high_score() -> i32 {
+    fs::read_to_string(HIGH_SCORE_FILE)
+        .ok()
+        .and_then(|contents| contents.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+// writes the high score back to disk - a failed write is non-fatal, it just won't persist
+fn save_high_score(score: i32) {
+    let _ = fs::write(HIGH_SCORE_FILE, score.to_string());
+}
+
 struct Player {
     x: i32, // x position = world-space position of the player & player will always render from the left
     y: f32, // y position = vertical position in the world
@@ -72,6 +104,7 @@ enum GameMode {
     Menu,
     Playing,
     End,
+    Paused,
 }
 
 // state represents a snapshot of the current game
@@ -79,23 +112,33 @@ struct State {
     // player and frame_time added to the state struct
     player: Player,
     frame_time: f32,
-    // State now tracks the current obstacle in play
-    obstacle: Obstacle,
+    // State now tracks every obstacle currently scrolling toward the player
+    obstacles: VecDeque<Obstacle>,
     mode: GameMode,
     // State now tracks the player's score based on how many obstacles hit
     score: i32,
+    // the best score ever achieved, loaded from disk on startup and persisted on each new record
+    high_score: i32,
+    // whether this run just broke the high score, so dead() knows when to show "New best!"
+    just_set_record: bool,
 }
 
 // Creating a constructor to initialize the State struct
 impl State {
     fn new() -> Self {
+        let mut obstacles = VecDeque::new();
+        let (gap, _) = difficulty(0);
+        obstacles.push_back(Obstacle::new(SCREEN_WIDTH, gap));
+
         State {
             // now the player construct exists you need to add it to the State constructor
             player: Player::new(5, 25), // Player positioned slightly right of the left side of screen
             frame_time: 0.0,            // frame_time initialized to 0 at the start
-            obstacle: Obstacle::new(SCREEN_WIDTH, 0),
+            obstacles,
             mode: GameMode::Menu,
             score: 0,
+            high_score: load_high_score(),
+            just_set_record: false,
         }
     }
 
@@ -122,16 +165,23 @@ impl State {
         // slows down the game to a more manageable rate
         self.frame_time += ctx.frame_time_ms; // frame_time_ms contains the time elapsed since the last time tick was called
 
-        // if frame_time exceeds FRAME_DURATION constant it is time to run the physics simulation and reset the frame to 0
-        if self.frame_time > FRAME_DURATION {
+        // the effective frame duration shrinks as the score rises, so the world scrolls faster over a long run
+        let (gap, frame_duration) = difficulty(self.score);
+
+        // if frame_time exceeds the current frame duration it is time to run the physics simulation and reset the frame to 0
+        if self.frame_time > frame_duration {
             // reset time to 0
             self.frame_time = 0.0;
             // physics simulation
             self.player.gravity_and_move();
         }
 
-        if let Some(VirtualKeyCode::Space) = ctx.key {
-            self.player.flap();
+        if let Some(key) = ctx.key {
+            match key {
+                VirtualKeyCode::Space => self.player.flap(),
+                VirtualKeyCode::P => self.mode = GameMode::Paused,
+                _ => {}
+            }
         }
 
         self.player.render(ctx);
@@ -139,24 +189,73 @@ impl State {
         // displays the player's current score underneath the instructions - does not send to stdout but returns a String
         ctx.print(0, 1, &format!("Score: {}", self.score));
 
-        self.obstacle.render(ctx, self.player.x);
-        if self.player.x > self.obstacle.x {
-            self.score += 1;
-            self.obstacle = Obstacle::new(self.player.x + SCREEN_WIDTH, self.score);
+        // spawn a new obstacle once the furthest-right one has scrolled within SPACING of the edge
+        if self
+            .obstacles
+            .back()
+            .map_or(true, |obstacle| obstacle.x < self.player.x + SCREEN_WIDTH - SPACING)
+        {
+            self.obstacles
+                .push_back(Obstacle::new(self.player.x + SCREEN_WIDTH, gap));
+        }
+
+        let mut hit = false;
+        for obstacle in self.obstacles.iter_mut() {
+            let screen_x = obstacle.x - self.player.x;
+            if (0..SCREEN_WIDTH).contains(&screen_x) {
+                obstacle.render(ctx, self.player.x);
+            }
+
+            if !obstacle.passed && self.player.x > obstacle.x {
+                obstacle.passed = true;
+                self.score += 1;
+            }
+
+            hit = hit || obstacle.hit_obstacle(&self.player);
+        }
+
+        // drop obstacles once they have scrolled off the left edge of the screen
+        while self.obstacles.front().map_or(false, |obstacle| obstacle.x < self.player.x) {
+            self.obstacles.pop_front();
         }
 
         // self.player.y needs to be casted to i32
-        if self.player.y as i32 > SCREEN_HEIGHT || self.obstacle.hit_obstacle(&self.player) {
+        if self.player.y as i32 > SCREEN_HEIGHT || hit {
+            if self.score > self.high_score {
+                self.high_score = self.score;
+                self.just_set_record = true;
+                save_high_score(self.high_score);
+            }
             self.mode = GameMode::End;
         }
     }
 
+    // pauses the game without touching frame_time, the player, the obstacle queue or the score
+    // so play() picks up exactly where it left off once resumed
+    fn paused(&mut self, ctx: &mut BTerm) {
+        ctx.print_color_centered(
+            SCREEN_HEIGHT / 2,
+            YELLOW,
+            BLACK,
+            "PAUSED - press P to resume",
+        );
+
+        if let Some(VirtualKeyCode::P) = ctx.key {
+            self.mode = GameMode::Playing;
+        }
+    }
+
     fn dead(&mut self, ctx: &mut BTerm) {
         ctx.cls();
         ctx.print_color_centered(5, RED, BLACK, "You are dead!");
         ctx.print_centered(6, &format!("You earned {} points", self.score));
-        ctx.print_color_centered(8, CYAN, BLACK, "(P) Play Again");
-        ctx.print_color_centered(9, CYAN, BLACK, "(Q) Quit Game");
+        if self.just_set_record {
+            ctx.print_color_centered(7, YELLOW, BLACK, &format!("New best! {} points", self.high_score));
+        } else {
+            ctx.print_centered(7, &format!("Best: {} points", self.high_score));
+        }
+        ctx.print_color_centered(9, CYAN, BLACK, "(P) Play Again");
+        ctx.print_color_centered(10, CYAN, BLACK, "(Q) Quit Game");
 
         if let Some(key) = ctx.key {
             match key {
@@ -172,9 +271,12 @@ impl State {
         // to correctly model a game restarting the player position needs to be reset and the frame_time reset to 0
         self.player = Player::new(5, SCREEN_WIDTH / 2);
         self.frame_time = 0.0;
-        self.obstacle = Obstacle::new(SCREEN_WIDTH, 0);
+        self.obstacles = VecDeque::new();
+        let (gap, _) = difficulty(0);
+        self.obstacles.push_back(Obstacle::new(SCREEN_WIDTH, gap));
         self.mode = GameMode::Playing;
         self.score = 0;
+        self.just_set_record = false;
     }
 }
 
@@ -182,17 +284,18 @@ struct Obstacle {
     x: i32,     // defines the obstacle's position in the world-space
     gap_y: i32, // defines the centre of the gap through which the dragon passes
     size: i32,  // defines the length of the gap in the obstacle
+    passed: bool, // tracks whether the player has already scored for clearing this obstacle
 }
 
 impl Obstacle {
-    fn new(x: i32, score: i32) -> Self {
+    fn new(x: i32, gap: i32) -> Self {
         // bracket-lib uses the xorshift algorithm to generate a pseudo-random number
         let mut random = RandomNumberGenerator::new();
         Obstacle {
             x,
             gap_y: random.range(5, 20), // obstacles will have a y value between 10 & 39
-            // gap's size is the maximum of (20 minus the player score, or 2)
-            size: i32::max(2, 10 - score), // ensures that the gaps decrease but never less than 2
+            size: gap, // gap size is computed up-front by difficulty() and handed in
+            passed: false,
         }
     }
 
@@ -240,6 +343,7 @@ impl GameState for State {
             GameMode::Menu => self.main_menu(ctx),
             GameMode::End => self.dead(ctx),
             GameMode::Playing => self.play(ctx),
+            GameMode::Paused => self.paused(ctx),
         }
     }
     /*